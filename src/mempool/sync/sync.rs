@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, VecDeque},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use bip300301::jsonrpsee::http_client::HttpClient;
@@ -9,9 +9,9 @@ use bitcoin::{
 };
 use educe::Educe;
 use futures::{stream, StreamExt as _, TryFutureExt as _};
-use hashlink::LinkedHashSet;
+use hashlink::{LinkedHashSet, LruCache};
 use thiserror::Error;
-use tokio::{spawn, sync::RwLock, task::JoinHandle};
+use tokio::{spawn, sync::broadcast, sync::RwLock, task::JoinHandle};
 
 use super::{
     super::Mempool, batched_request, BatchedResponseItem, CombinedStreamItem,
@@ -23,12 +23,317 @@ use crate::{
     zmq::{SequenceMessage, SequenceStream, SequenceStreamError},
 };
 
+/// Default maximum number of orphan txs held in the [`OrphanPool`].
+const DEFAULT_MAX_ORPHAN_COUNT: usize = 100;
+/// Default maximum total weight (weight units) of orphan txs held in the
+/// [`OrphanPool`].
+const DEFAULT_MAX_ORPHAN_WEIGHT: u64 = 100_000_000;
+
+/// Pool of txs that can't yet be added to the mempool because one or more of
+/// their inputs spend an as-yet-unknown parent tx. Orphans are indexed by
+/// each missing parent txid so that they can be retried as soon as that
+/// parent becomes known, transitively resolving chains of orphans.
+#[derive(Debug)]
+struct OrphanPoolInner {
+    /// Orphan tx bodies, keyed by their own txid.
+    orphans: HashMap<Txid, Transaction>,
+    /// For each orphan txid, the missing parent txids it's currently filed
+    /// under.
+    missing_parents: HashMap<Txid, LinkedHashSet<Txid>>,
+    /// For each missing parent txid, the orphans waiting on it.
+    waiting_on: HashMap<Txid, LinkedHashSet<Txid>>,
+    /// Insertion order of orphans, oldest first, for FIFO eviction.
+    insertion_order: LinkedHashSet<Txid>,
+    /// Running total of the weight of all orphans currently in the pool.
+    total_weight: u64,
+    max_count: usize,
+    max_weight: u64,
+}
+
+impl Default for OrphanPoolInner {
+    fn default() -> Self {
+        Self {
+            orphans: HashMap::new(),
+            missing_parents: HashMap::new(),
+            waiting_on: HashMap::new(),
+            insertion_order: LinkedHashSet::new(),
+            total_weight: 0,
+            max_count: DEFAULT_MAX_ORPHAN_COUNT,
+            max_weight: DEFAULT_MAX_ORPHAN_WEIGHT,
+        }
+    }
+}
+
+impl OrphanPoolInner {
+    /// Number of orphan txs currently held in the pool.
+    fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Insert an orphan tx, indexed under each of its missing parents.
+    /// Evicts the oldest orphans (FIFO) until the pool is back within
+    /// `max_count` / `max_weight`, except that the just-inserted orphan is
+    /// never evicted: if it alone exceeds `max_weight`, it stays as the only
+    /// orphan in the pool.
+    /// Returns the txids of any orphans evicted to make room, so callers can
+    /// record why they were dropped.
+    fn insert<Parents>(
+        &mut self,
+        txid: Txid,
+        tx: Transaction,
+        missing_parents: Parents,
+    ) -> Vec<Txid>
+    where
+        Parents: IntoIterator<Item = Txid>,
+    {
+        if self.orphans.contains_key(&txid) {
+            return Vec::new();
+        }
+        let weight = tx.weight().to_wu();
+        let missing_parents: LinkedHashSet<Txid> =
+            missing_parents.into_iter().collect();
+        for parent_txid in &missing_parents {
+            self.waiting_on.entry(*parent_txid).or_default().replace(txid);
+        }
+        self.missing_parents.insert(txid, missing_parents);
+        self.orphans.insert(txid, tx);
+        self.insertion_order.replace(txid);
+        self.total_weight += weight;
+        let mut evicted = Vec::new();
+        while self.orphans.len() > self.max_count
+            || self.total_weight > self.max_weight
+        {
+            let Some(&oldest) = self.insertion_order.front() else {
+                break;
+            };
+            if oldest == txid && self.orphans.len() == 1 {
+                break;
+            }
+            tracing::debug!(%oldest, "Evicting orphan tx (pool full)");
+            if self.remove(&oldest).is_some() {
+                evicted.push(oldest);
+            }
+        }
+        evicted
+    }
+
+    /// Remove an orphan tx from the pool, dropping it from every
+    /// missing-parent index it was filed under.
+    fn remove(&mut self, txid: &Txid) -> Option<Transaction> {
+        let tx = self.orphans.remove(txid)?;
+        self.total_weight -= tx.weight().to_wu();
+        self.insertion_order.remove(txid);
+        if let Some(parent_txids) = self.missing_parents.remove(txid) {
+            for parent_txid in parent_txids {
+                if let Some(waiting) = self.waiting_on.get_mut(&parent_txid) {
+                    waiting.remove(txid);
+                    if waiting.is_empty() {
+                        self.waiting_on.remove(&parent_txid);
+                    }
+                }
+            }
+        }
+        Some(tx)
+    }
+
+    /// Remove and return the orphan txids that were waiting on
+    /// `parent_txid`, now that it may be available. Callers should retry
+    /// each of them, which may in turn resolve further orphans.
+    fn take_waiting_on(&mut self, parent_txid: &Txid) -> LinkedHashSet<Txid> {
+        self.waiting_on.remove(parent_txid).unwrap_or_default()
+    }
+
+    /// Drop every orphan waiting on `parent_txid`, e.g. because the parent's
+    /// outputs are provably spent or confirmed elsewhere and the orphan can
+    /// never be completed. Prevents such orphans from lingering forever.
+    /// Returns the evicted orphan txids, so callers can record why they were
+    /// dropped.
+    fn evict_parent(&mut self, parent_txid: &Txid) -> Vec<Txid> {
+        let mut evicted = Vec::new();
+        for orphan_txid in self.take_waiting_on(parent_txid) {
+            tracing::debug!(%orphan_txid, %parent_txid, "Evicting orphan tx (parent unresolvable)");
+            if self.remove(&orphan_txid).is_some() {
+                evicted.push(orphan_txid);
+            }
+        }
+        evicted
+    }
+}
+
+/// Shared handle to an [`OrphanPoolInner`], so that the orphan count can be
+/// reported via [`MempoolSync::info`] without handing out the sync task's
+/// mutable state.
+#[derive(Debug, Default)]
+struct OrphanPool(Mutex<OrphanPoolInner>);
+
+impl OrphanPool {
+    /// Number of orphan txs currently held in the pool.
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    fn insert<Parents>(
+        &self,
+        txid: Txid,
+        tx: Transaction,
+        missing_parents: Parents,
+    ) -> Vec<Txid>
+    where
+        Parents: IntoIterator<Item = Txid>,
+    {
+        self.0.lock().unwrap().insert(txid, tx, missing_parents)
+    }
+
+    fn remove(&self, txid: &Txid) -> Option<Transaction> {
+        self.0.lock().unwrap().remove(txid)
+    }
+
+    fn take_waiting_on(&self, parent_txid: &Txid) -> LinkedHashSet<Txid> {
+        self.0.lock().unwrap().take_waiting_on(parent_txid)
+    }
+
+    fn evict_parent(&self, parent_txid: &Txid) -> Vec<Txid> {
+        self.0.lock().unwrap().evict_parent(parent_txid)
+    }
+}
+
+/// Default capacity of the recently-rejected-txs cache.
+const DEFAULT_MAX_RECENT_REJECTS: usize = 1_000;
+
+/// Why a tx was not (or could no longer be) added to the synced mempool.
+#[derive(Clone, Copy, Debug)]
+pub enum RejectReason {
+    /// The [`CusfEnforcer`] declined to accept the tx.
+    EnforcerRejected,
+    /// Computing `value_in - value_out` would've overflowed.
+    FeeOverflow,
+    /// One or more of the tx's inputs could not be resolved.
+    MissingInputs,
+    /// The tx was replaced by a conflicting tx.
+    Replaced,
+    /// The tx's fee rate was too low to accept.
+    LowFeeRate,
+}
+
+/// A tx rejection, as broadcast to subscribers of
+/// [`MempoolSync::subscribe_rejects`].
+#[derive(Clone, Copy, Debug)]
+pub struct TxRejected {
+    pub txid: Txid,
+    pub reason: RejectReason,
+}
+
+/// Tracks recently rejected txids, and broadcasts rejections as they happen
+/// so that enforcer authors can learn *why* a tx never entered the synced
+/// mempool.
+#[derive(Debug)]
+struct RejectTracker {
+    recent: Mutex<LruCache<Txid, RejectReason>>,
+    sender: broadcast::Sender<TxRejected>,
+}
+
+impl RejectTracker {
+    fn new() -> Self {
+        let (sender, _receiver) = broadcast::channel(DEFAULT_MAX_RECENT_REJECTS);
+        Self {
+            recent: Mutex::new(LruCache::new(DEFAULT_MAX_RECENT_REJECTS)),
+            sender,
+        }
+    }
+
+    /// Record a rejection and notify subscribers. Subscribers that lag too
+    /// far behind simply miss old events; this never blocks.
+    fn reject(&self, txid: Txid, reason: RejectReason) {
+        self.recent.lock().unwrap().insert(txid, reason);
+        let _receiver_count = self.sender.send(TxRejected { txid, reason });
+    }
+
+    fn get(&self, txid: &Txid) -> Option<RejectReason> {
+        self.recent.lock().unwrap().get(txid).copied()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TxRejected> {
+        self.sender.subscribe()
+    }
+}
+
+/// Default capacity of the mempool event broadcast channel.
+const DEFAULT_MAX_BUFFERED_EVENTS: usize = 1_000;
+
+/// A change to the synced mempool or chain tip, as broadcast to subscribers
+/// of [`MempoolSync::subscribe`]. Lets consumers react in real time instead
+/// of busy-polling [`MempoolSync::with_mempool`].
+#[derive(Clone, Copy, Debug)]
+pub enum MempoolEvent {
+    /// A tx was added to the mempool.
+    TxAdded { txid: Txid, fee: u64, vsize: u64 },
+    /// A tx left the mempool (e.g. confirmed, or conflicted out). A tx
+    /// dropped for conflicting with a reinserted disconnected-block tx is
+    /// also reported as [`MempoolEvent::TxRejected`] with
+    /// [`RejectReason::Replaced`].
+    TxRemoved { txid: Txid },
+    /// A tx was declined, or dropped from the mempool as replaced, and will
+    /// not (re-)enter it.
+    TxRejected { txid: Txid, reason: RejectReason },
+    /// The chain tip advanced to a newly connected block.
+    BlockConnected { hash: BlockHash },
+    /// A block was disconnected from the chain tip.
+    BlockDisconnected { hash: BlockHash },
+}
+
+/// Bundles the shared handles threaded through every mempool-mutating
+/// function (reject tracking, the orphan pool, the event broadcast sender,
+/// and the incrementally maintained stats/ancestor indexes) into a single
+/// parameter, instead of five parallel ones. The whole bundle is shared
+/// with [`MempoolSync`] behind one `Arc`.
+#[derive(Debug)]
+struct SyncContext {
+    rejects: RejectTracker,
+    orphans: OrphanPool,
+    events: broadcast::Sender<MempoolEvent>,
+    stats: MempoolStats,
+    ancestor_index: AncestorIndex,
+}
+
+impl SyncContext {
+    fn new() -> Self {
+        let (events, _receiver) = broadcast::channel(DEFAULT_MAX_BUFFERED_EVENTS);
+        Self {
+            rejects: RejectTracker::new(),
+            orphans: OrphanPool::default(),
+            events,
+            stats: MempoolStats::default(),
+            ancestor_index: AncestorIndex::default(),
+        }
+    }
+
+    /// Record a [`RejectReason::MissingInputs`] rejection for each orphan
+    /// txid evicted by [`OrphanPool::insert`] or [`OrphanPool::evict_parent`],
+    /// so they don't vanish from [`MempoolSync::subscribe_rejects`] with no
+    /// trace.
+    fn reject_evicted_orphans(&self, evicted: Vec<Txid>) {
+        for txid in evicted {
+            self.rejects.reject(txid, RejectReason::MissingInputs);
+            let _subscriber_count = self.events.send(MempoolEvent::TxRejected {
+                txid,
+                reason: RejectReason::MissingInputs,
+            });
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SyncState {
     request_queue: RequestQueue,
     seq_message_queue: VecDeque<SequenceMessage>,
     /// Txs not needed in mempool, but requested in order to determine fees
     tx_cache: HashMap<Txid, Transaction>,
+    /// Txids from a disconnected block that weren't yet in `tx_cache` when
+    /// [`reinsert_disconnected_block_txs`] ran over them, so their fetch was
+    /// requested rather than retried immediately. Once such a txid's body
+    /// lands in `tx_cache`, [`handle_resp`] re-drives it through
+    /// `try_add_tx_from_cache` instead of leaving it stranded in the cache.
+    pending_reinsert: std::collections::HashSet<Txid>,
 }
 
 #[derive(Educe)]
@@ -42,8 +347,6 @@ where
     CombinedStreamEnded,
     #[error("CUSF enforcer error")]
     CusfEnforcer(#[source] Enforcer::AcceptTxError),
-    #[error("Fee overflow")]
-    FeeOverflow,
     #[error(transparent)]
     MempoolInsert(#[from] MempoolInsertError),
     #[error(transparent)]
@@ -101,39 +404,139 @@ async fn handle_seq_message(
     sync_state.seq_message_queue.push_back(seq_msg);
 }
 
-fn handle_resp_tx(sync_state: &mut SyncState, tx: Transaction) {
+// returns the txid of the tx that was cached
+fn handle_resp_tx(sync_state: &mut SyncState, tx: Transaction) -> Txid {
     let txid = tx.compute_txid();
     sync_state.tx_cache.insert(txid, tx);
+    txid
 }
 
-fn handle_resp_block(
+/// Re-insert every tx in a disconnected block into the mempool, fetching
+/// missing inputs and deferring via the orphan pool just like any other
+/// candidate tx (see [`try_add_tx_from_cache`]), then drop any mempool txs
+/// that now conflict with what was reinserted.
+fn reinsert_disconnected_block_txs<Enforcer>(
+    enforcer: &mut Enforcer,
     mempool: &mut Mempool,
     sync_state: &mut SyncState,
-    block: bip300301::client::Block,
+    ctx: &SyncContext,
+    txids: &[Txid],
+) -> Result<(), SyncTaskError<Enforcer>>
+where
+    Enforcer: CusfEnforcer,
+{
+    for txid in txids {
+        if !sync_state.tx_cache.contains_key(txid) {
+            sync_state
+                .request_queue
+                .push_front(RequestItem::Tx(*txid, true));
+            sync_state.pending_reinsert.insert(*txid);
+            continue;
+        }
+        let _readded: bool =
+            try_add_tx_from_cache(enforcer, mempool, sync_state, ctx, txid)?;
+    }
+    let () = drop_conflicting_txs(mempool, sync_state, ctx, txids)?;
+    Ok(())
+}
+
+/// Drop mempool txs that double-spend an input now consumed by a
+/// just-reinserted tx from a disconnected block.
+fn drop_conflicting_txs(
+    mempool: &mut Mempool,
+    sync_state: &mut SyncState,
+    ctx: &SyncContext,
+    reinserted: &[Txid],
 ) -> Result<(), MempoolRemoveError> {
+    let mut spent_by_reinserted: HashMap<OutPoint, Txid> = HashMap::new();
+    for txid in reinserted {
+        if let Some((tx, _)) = mempool.txs.0.get(txid) {
+            for input in &tx.input {
+                spent_by_reinserted.insert(input.previous_output, *txid);
+            }
+        }
+    }
+    let conflicting: Vec<Txid> = mempool
+        .txs
+        .0
+        .iter()
+        .filter(|(txid, (tx, _))| {
+            !reinserted.contains(txid)
+                && tx.input.iter().any(|input| {
+                    spent_by_reinserted.contains_key(&input.previous_output)
+                })
+        })
+        .map(|(txid, _)| *txid)
+        .collect();
+    for txid in conflicting {
+        if let Some((tx, fee_sat)) = mempool.remove(&txid)? {
+            ctx.stats.record_remove(&txid, &tx, fee_sat);
+            ctx.ancestor_index.record_remove(&txid);
+        }
+        sync_state
+            .request_queue
+            .remove(&RequestItem::Tx(txid, true));
+        ctx.reject_evicted_orphans(ctx.orphans.evict_parent(&txid));
+        ctx.rejects.reject(txid, RejectReason::Replaced);
+        let _subscriber_count =
+            ctx.events.send(MempoolEvent::TxRemoved { txid });
+        let _subscriber_count = ctx.events.send(MempoolEvent::TxRejected {
+            txid,
+            reason: RejectReason::Replaced,
+        });
+    }
+    Ok(())
+}
+
+fn handle_resp_block<Enforcer>(
+    enforcer: &mut Enforcer,
+    mempool: &mut Mempool,
+    sync_state: &mut SyncState,
+    ctx: &SyncContext,
+    block: bip300301::client::Block,
+) -> Result<(), SyncTaskError<Enforcer>>
+where
+    Enforcer: CusfEnforcer,
+{
     match sync_state.seq_message_queue.front() {
         Some(SequenceMessage::BlockHashConnected(block_hash, _))
             if *block_hash == block.hash =>
         {
             for txid in &block.tx {
-                let _removed: Option<_> = mempool.remove(txid)?;
+                let removed = mempool.remove(txid)?;
                 sync_state
                     .request_queue
                     .remove(&RequestItem::Tx(*txid, true));
+                if let Some((tx, fee_sat)) = removed {
+                    ctx.stats.record_remove(txid, &tx, fee_sat);
+                    ctx.ancestor_index.record_remove(txid);
+                    let _subscriber_count = ctx
+                        .events
+                        .send(MempoolEvent::TxRemoved { txid: *txid });
+                }
+                // The parent just confirmed, so it will never itself enter
+                // the mempool: any orphan still waiting on it would
+                // otherwise only get cleaned up once it ages out by FIFO.
+                ctx.reject_evicted_orphans(ctx.orphans.evict_parent(txid));
             }
             mempool.chain.tip = block.hash;
+            let _subscriber_count = ctx
+                .events
+                .send(MempoolEvent::BlockConnected { hash: block.hash });
             sync_state.seq_message_queue.pop_front();
         }
         Some(SequenceMessage::BlockHashDisconnected(block_hash, _))
             if *block_hash == block.hash && mempool.chain.tip == block.hash =>
         {
-            for txid in &block.tx {
-                // FIXME: insert without info
-                let () = todo!();
-            }
+            let () = reinsert_disconnected_block_txs(
+                enforcer, mempool, sync_state, ctx, &block.tx,
+            )?;
             mempool.chain.tip = block
                 .previousblockhash
                 .unwrap_or_else(|| BlockHash::all_zeros());
+            let _subscriber_count = ctx
+                .events
+                .send(MempoolEvent::BlockDisconnected { hash: block.hash });
             sync_state.seq_message_queue.pop_front();
         }
         Some(_) | None => (),
@@ -147,12 +550,13 @@ fn try_add_tx_from_cache<Enforcer>(
     enforcer: &mut Enforcer,
     mempool: &mut Mempool,
     sync_state: &mut SyncState,
+    ctx: &SyncContext,
     txid: &Txid,
 ) -> Result<bool, SyncTaskError<Enforcer>>
 where
     Enforcer: CusfEnforcer,
 {
-    let Some(tx) = sync_state.tx_cache.get(txid) else {
+    let Some(tx) = sync_state.tx_cache.get(txid).cloned() else {
         return Ok(false);
     };
     let (mut value_in, value_out) = (Some(Amount::ZERO), Amount::ZERO);
@@ -176,38 +580,146 @@ where
         let value = input_tx.output[vout as usize].value;
         value_in = value_in.map(|value_in| value_in + value);
     }
-    for input_txid in input_txs_needed.into_iter().rev() {
-        sync_state
-            .request_queue
-            .push_front(RequestItem::Tx(input_txid, false))
+    if !input_txs_needed.is_empty() {
+        for input_txid in input_txs_needed.iter().rev() {
+            sync_state
+                .request_queue
+                .push_front(RequestItem::Tx(*input_txid, false))
+        }
+        tracing::trace!("orphaning {txid}, waiting on {input_txs_needed:?}");
+        let evicted = ctx.orphans.insert(*txid, tx, input_txs_needed);
+        ctx.reject_evicted_orphans(evicted);
+        return Ok(false);
     }
     let Some(value_in) = value_in else {
         return Ok(false);
     };
     let Some(fee_delta) = value_in.checked_sub(value_out) else {
-        return Err(SyncTaskError::FeeOverflow);
+        tracing::trace!("rejecting {txid}: fee overflow");
+        ctx.rejects.reject(*txid, RejectReason::FeeOverflow);
+        let _subscriber_count = ctx.events.send(MempoolEvent::TxRejected {
+            txid: *txid,
+            reason: RejectReason::FeeOverflow,
+        });
+        sync_state.tx_cache.remove(txid);
+        sync_state
+            .request_queue
+            .remove(&RequestItem::Tx(*txid, true));
+        return Ok(true);
     };
     if enforcer
-        .accept_tx(tx)
+        .accept_tx(&tx)
         .map_err(SyncTaskError::CusfEnforcer)?
     {
-        mempool.insert(tx.clone(), fee_delta.to_sat())?;
+        let vsize = tx.vsize() as u64;
+        let fee_sat = fee_delta.to_sat();
+        mempool.insert(tx.clone(), fee_sat)?;
+        ctx.stats.record_insert(*txid, &tx, fee_sat);
+        ctx.ancestor_index.record_insert(*txid, &tx, fee_sat);
         tracing::trace!("added {txid} to mempool");
+        let _subscriber_count = ctx.events.send(MempoolEvent::TxAdded {
+            txid: *txid,
+            fee: fee_sat,
+            vsize,
+        });
+        let () =
+            try_resolve_orphans(enforcer, mempool, sync_state, ctx, txid)?;
     } else {
-        // FIXME: reject tx
-        todo!();
         tracing::trace!("rejecting {txid}");
+        ctx.rejects.reject(*txid, RejectReason::EnforcerRejected);
+        let _subscriber_count = ctx.events.send(MempoolEvent::TxRejected {
+            txid: *txid,
+            reason: RejectReason::EnforcerRejected,
+        });
+        sync_state.tx_cache.remove(txid);
+        sync_state
+            .request_queue
+            .remove(&RequestItem::Tx(*txid, true));
     }
     let mempool_txs = mempool.txs.0.len();
     tracing::debug!(%mempool_txs, "Syncing...");
     Ok(true)
 }
 
+/// Retry every orphan tx that was waiting on `resolved_txid`, now that it's
+/// available (either inserted into the mempool, or simply fetched into the
+/// tx cache so its outputs can be used for fee computation). Resolution is
+/// transitive: each retried orphan that's accepted will itself trigger
+/// `try_resolve_orphans` for its own dependents.
+fn try_resolve_orphans<Enforcer>(
+    enforcer: &mut Enforcer,
+    mempool: &mut Mempool,
+    sync_state: &mut SyncState,
+    ctx: &SyncContext,
+    resolved_txid: &Txid,
+) -> Result<(), SyncTaskError<Enforcer>>
+where
+    Enforcer: CusfEnforcer,
+{
+    for orphan_txid in ctx.orphans.take_waiting_on(resolved_txid) {
+        if ctx.orphans.remove(&orphan_txid).is_none() {
+            continue;
+        }
+        tracing::trace!("retrying orphan {orphan_txid}");
+        let _retried: bool = try_add_tx_from_cache(
+            enforcer,
+            mempool,
+            sync_state,
+            ctx,
+            &orphan_txid,
+        )?;
+    }
+    Ok(())
+}
+
+/// Re-drive [`reinsert_disconnected_block_txs`] for `fetched_txid`, if it was
+/// awaiting this tx's body (see [`SyncState::pending_reinsert`]). Without
+/// this, a disconnected-block tx not yet in `tx_cache` would have its fetch
+/// requested but never actually get reinserted once that fetch completes.
+fn try_resolve_pending_reinsert<Enforcer>(
+    enforcer: &mut Enforcer,
+    mempool: &mut Mempool,
+    sync_state: &mut SyncState,
+    ctx: &SyncContext,
+    fetched_txid: &Txid,
+) -> Result<(), SyncTaskError<Enforcer>>
+where
+    Enforcer: CusfEnforcer,
+{
+    if !sync_state.pending_reinsert.remove(fetched_txid) {
+        return Ok(());
+    }
+    tracing::trace!("retrying disconnected-block reinsert {fetched_txid}");
+    let readded: bool = try_add_tx_from_cache(
+        enforcer,
+        mempool,
+        sync_state,
+        ctx,
+        fetched_txid,
+    )?;
+    if readded {
+        // A deferred reinsert runs outside the synchronous sweep in
+        // `reinsert_disconnected_block_txs`, so conflicts keyed on this
+        // tx's inputs were never checked for there -- do it now, same as
+        // the synchronous path does for txs already cached at disconnect
+        // time. Otherwise a mempool tx double-spending this reinserted tx
+        // would never get evicted.
+        let () = drop_conflicting_txs(
+            mempool,
+            sync_state,
+            ctx,
+            std::slice::from_ref(fetched_txid),
+        )?;
+    }
+    Ok(())
+}
+
 // returns `true` if an item was applied successfully
 fn try_apply_next_seq_message<Enforcer>(
     enforcer: &mut Enforcer,
     mempool: &mut Mempool,
     sync_state: &mut SyncState,
+    ctx: &SyncContext,
 ) -> Result<bool, SyncTaskError<Enforcer>>
 where
     Enforcer: CusfEnforcer,
@@ -221,13 +733,16 @@ where
                 let Some(block) = mempool.chain.blocks.get(block_hash) else {
                     break 'res false;
                 };
-                for txid in &block.tx {
-                    // FIXME: insert without info
-                    let () = todo!();
-                }
-                mempool.chain.tip = block
-                    .previousblockhash
-                    .unwrap_or_else(|| BlockHash::all_zeros());
+                let hash = block.hash;
+                let txids = block.tx.clone();
+                let previousblockhash = block.previousblockhash;
+                let () = reinsert_disconnected_block_txs(
+                    enforcer, mempool, sync_state, ctx, &txids,
+                )?;
+                mempool.chain.tip =
+                    previousblockhash.unwrap_or_else(BlockHash::all_zeros);
+                let _subscriber_count =
+                    ctx.events.send(MempoolEvent::BlockDisconnected { hash });
                 true
             }
             Some(SequenceMessage::TxHashAdded {
@@ -236,7 +751,7 @@ where
                 zmq_seq: _,
             }) => {
                 let txid = *txid;
-                try_add_tx_from_cache(enforcer, mempool, sync_state, &txid)?
+                try_add_tx_from_cache(enforcer, mempool, sync_state, ctx, &txid)?
             }
             Some(SequenceMessage::TxHashRemoved {
                 txid,
@@ -244,7 +759,19 @@ where
                 zmq_seq: _,
             }) => {
                 // FIXME: review -- looks sus
-                mempool.remove(txid)?.is_some()
+                let removed = mempool.remove(txid)?;
+                // The tx is gone without being confirmed (e.g. conflicted
+                // out by a replacement), so any orphan waiting on it can
+                // never be completed.
+                ctx.reject_evicted_orphans(ctx.orphans.evict_parent(txid));
+                if let Some((tx, fee_sat)) = &removed {
+                    ctx.stats.record_remove(txid, tx, *fee_sat);
+                    ctx.ancestor_index.record_remove(txid);
+                    let _subscriber_count = ctx
+                        .events
+                        .send(MempoolEvent::TxRemoved { txid: *txid });
+                }
+                removed.is_some()
             }
             Some(SequenceMessage::BlockHashConnected(_, _)) | None => false,
         }
@@ -259,6 +786,7 @@ async fn handle_resp<Enforcer>(
     enforcer: &mut Enforcer,
     mempool: &RwLock<Mempool>,
     sync_state: &mut SyncState,
+    ctx: &SyncContext,
     resp: BatchedResponseItem,
 ) -> Result<(), SyncTaskError<Enforcer>>
 where
@@ -278,7 +806,21 @@ where
                         }
                     }
                 }
-                let () = handle_resp_tx(sync_state, tx);
+                let txid = handle_resp_tx(sync_state, tx);
+                let () = try_resolve_orphans(
+                    enforcer,
+                    &mut mempool_write,
+                    sync_state,
+                    ctx,
+                    &txid,
+                )?;
+                let () = try_resolve_pending_reinsert(
+                    enforcer,
+                    &mut mempool_write,
+                    sync_state,
+                    ctx,
+                    &txid,
+                )?;
             }
             for input_txid in input_txs_needed.into_iter().rev() {
                 sync_state
@@ -289,7 +831,13 @@ where
         BatchedResponseItem::Single(ResponseItem::Block(block)) => {
             // FIXME: remove
             tracing::debug!("Handling block {}", block.hash);
-            let () = handle_resp_block(&mut mempool_write, sync_state, block)?;
+            let () = handle_resp_block(
+                enforcer,
+                &mut mempool_write,
+                sync_state,
+                ctx,
+                block,
+            )?;
         }
         BatchedResponseItem::Single(ResponseItem::Tx(tx, in_mempool)) => {
             let mut input_txs_needed = LinkedHashSet::new();
@@ -302,7 +850,21 @@ where
                     }
                 }
             }
-            let () = handle_resp_tx(sync_state, tx);
+            let txid = handle_resp_tx(sync_state, tx);
+            let () = try_resolve_orphans(
+                enforcer,
+                &mut mempool_write,
+                sync_state,
+                ctx,
+                &txid,
+            )?;
+            let () = try_resolve_pending_reinsert(
+                enforcer,
+                &mut mempool_write,
+                sync_state,
+                ctx,
+                &txid,
+            )?;
             for input_txid in input_txs_needed.into_iter().rev() {
                 sync_state
                     .request_queue
@@ -310,15 +872,19 @@ where
             }
         }
     }
-    while try_apply_next_seq_message(enforcer, &mut mempool_write, sync_state)?
-    {
-    }
+    while try_apply_next_seq_message(
+        enforcer,
+        &mut mempool_write,
+        sync_state,
+        ctx,
+    )? {}
     Ok(())
 }
 
 async fn task<Enforcer>(
     mut enforcer: Enforcer,
     mempool: Arc<RwLock<Mempool>>,
+    ctx: Arc<SyncContext>,
     rpc_client: HttpClient,
     sequence_stream: SequenceStream<'static>,
 ) -> Result<(), SyncTaskError<Enforcer>>
@@ -351,6 +917,7 @@ where
                     &mut enforcer,
                     &mempool,
                     &mut sync_state,
+                    &ctx,
                     resp?,
                 )
                 .await?;
@@ -359,8 +926,362 @@ where
     }
 }
 
+/// A candidate block template: txids in the order they should be included
+/// (every tx's in-mempool ancestors appear before it), each tx's own fee,
+/// and the template's totals.
+#[derive(Clone, Debug, Default)]
+pub struct BlockTemplate {
+    pub txids: Vec<Txid>,
+    /// Each included tx's own fee (sats), keyed by txid.
+    pub fees: HashMap<Txid, u64>,
+    pub total_fee_sat: u64,
+    pub total_weight: u64,
+}
+
+/// Per-tx ancestor/descendant bookkeeping backing [`build_block_template`],
+/// maintained incrementally by [`AncestorIndex::record_insert`]/
+/// [`AncestorIndex::record_remove`] in lockstep with every
+/// `Mempool::insert`/`remove`, rather than rebuilt by walking the whole
+/// mempool on every `block_template()` call.
+#[derive(Clone, Debug, Default)]
+struct AncestorEntry {
+    fee: u64,
+    weight: u64,
+    /// Direct in-mempool parents, for topological ordering within a
+    /// package.
+    parents: std::collections::HashSet<Txid>,
+    /// All in-mempool ancestors, including self.
+    ancestors: std::collections::HashSet<Txid>,
+    /// All in-mempool descendants, excluding self.
+    descendants: std::collections::HashSet<Txid>,
+    /// Sum of `fee` over `ancestors`.
+    ancestor_fee: u64,
+    /// Sum of `weight` over `ancestors`.
+    ancestor_weight: u64,
+}
+
+#[derive(Debug, Default)]
+struct AncestorIndexInner {
+    entries: HashMap<Txid, AncestorEntry>,
+}
+
+impl AncestorIndexInner {
+    fn record_insert(&mut self, txid: Txid, tx: &Transaction, fee: u64) {
+        if self.entries.contains_key(&txid) {
+            return;
+        }
+        let weight = tx.weight().to_wu();
+        let parents: std::collections::HashSet<Txid> = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|parent_txid| self.entries.contains_key(parent_txid))
+            .collect();
+        let mut ancestors = std::collections::HashSet::new();
+        ancestors.insert(txid);
+        for parent_txid in &parents {
+            ancestors.extend(self.entries[parent_txid].ancestors.iter());
+        }
+        let ancestor_fee = fee
+            + ancestors
+                .iter()
+                .filter(|ancestor_txid| **ancestor_txid != txid)
+                .map(|ancestor_txid| self.entries[ancestor_txid].fee)
+                .sum::<u64>();
+        let ancestor_weight = weight
+            + ancestors
+                .iter()
+                .filter(|ancestor_txid| **ancestor_txid != txid)
+                .map(|ancestor_txid| self.entries[ancestor_txid].weight)
+                .sum::<u64>();
+        for ancestor_txid in
+            ancestors.iter().filter(|ancestor_txid| **ancestor_txid != txid)
+        {
+            self.entries
+                .get_mut(ancestor_txid)
+                .unwrap()
+                .descendants
+                .insert(txid);
+        }
+        self.entries.insert(
+            txid,
+            AncestorEntry {
+                fee,
+                weight,
+                parents,
+                ancestors,
+                descendants: std::collections::HashSet::new(),
+                ancestor_fee,
+                ancestor_weight,
+            },
+        );
+    }
+
+    /// Remove `txid`'s entry, unwinding it from every ancestor's
+    /// `descendants` set and every descendant's `ancestors`/`ancestor_fee`/
+    /// `ancestor_weight`.
+    fn record_remove(&mut self, txid: &Txid) {
+        let Some(entry) = self.entries.remove(txid) else {
+            return;
+        };
+        for descendant_txid in &entry.descendants {
+            if let Some(descendant) = self.entries.get_mut(descendant_txid) {
+                descendant.ancestors.remove(txid);
+                descendant.ancestor_fee -= entry.fee;
+                descendant.ancestor_weight -= entry.weight;
+            }
+        }
+        for ancestor_txid in
+            entry.ancestors.iter().filter(|ancestor_txid| *ancestor_txid != txid)
+        {
+            if let Some(ancestor) = self.entries.get_mut(ancestor_txid) {
+                ancestor.descendants.remove(txid);
+            }
+        }
+    }
+}
+
+/// Shared handle to an [`AncestorIndexInner`], so [`MempoolSync::block_template`]
+/// can read it without the sync task's mutable state.
+#[derive(Debug, Default)]
+struct AncestorIndex(Mutex<AncestorIndexInner>);
+
+impl AncestorIndex {
+    fn record_insert(&self, txid: Txid, tx: &Transaction, fee: u64) {
+        self.0.lock().unwrap().record_insert(txid, tx, fee);
+    }
+
+    fn record_remove(&self, txid: &Txid) {
+        self.0.lock().unwrap().record_remove(txid);
+    }
+
+    /// Snapshot of the current per-tx ancestor entries, for
+    /// [`build_block_template`] to assemble a package from.
+    fn snapshot(&self) -> HashMap<Txid, AncestorEntry> {
+        self.0.lock().unwrap().entries.clone()
+    }
+}
+
+/// Post-order DFS over `txid`'s in-mempool ancestors that are still in
+/// `package`, so that parents always come out before their children.
+fn topo_order_package(
+    txid: Txid,
+    entries: &HashMap<Txid, AncestorEntry>,
+    package: &std::collections::HashSet<Txid>,
+    visited: &mut std::collections::HashSet<Txid>,
+    order: &mut Vec<Txid>,
+) {
+    if !visited.insert(txid) {
+        return;
+    }
+    for parent_txid in &entries[&txid].parents {
+        if package.contains(parent_txid) {
+            topo_order_package(*parent_txid, entries, package, visited, order);
+        }
+    }
+    order.push(txid);
+}
+
+/// Greedily assemble a block template ordered by ancestor (CPFP) fee rate:
+/// repeatedly pick the highest ancestor-fee-rate tx whose whole unincluded
+/// in-mempool ancestor package still fits the remaining weight budget, and
+/// include that package in topological order, updating the ancestor totals
+/// of its descendants before the next pick. Candidates whose package didn't
+/// fit are re-evaluated on every pick rather than excluded for good, since
+/// a shared ancestor being pulled in by another package can shrink their
+/// own package enough to fit later.
+fn build_block_template(
+    entries: &HashMap<Txid, AncestorEntry>,
+    max_weight: u64,
+) -> BlockTemplate {
+    // ancestor_fee / ancestor_weight over all *currently unincluded*
+    // ancestors, updated as packages get included.
+    let mut remaining_fee: HashMap<Txid, u64> = entries
+        .iter()
+        .map(|(txid, entry)| (*txid, entry.ancestor_fee))
+        .collect();
+    let mut remaining_weight: HashMap<Txid, u64> = entries
+        .iter()
+        .map(|(txid, entry)| (*txid, entry.ancestor_weight))
+        .collect();
+
+    let mut included: std::collections::HashSet<Txid> =
+        std::collections::HashSet::new();
+    let mut template = BlockTemplate::default();
+    let mut budget_left = max_weight;
+
+    loop {
+        let mut candidates: Vec<Txid> = entries
+            .keys()
+            .filter(|txid| !included.contains(*txid))
+            .copied()
+            .collect();
+        candidates.sort_by(|a, b| {
+            let rate_a = remaining_fee[a] as f64 / remaining_weight[a].max(1) as f64;
+            let rate_b = remaining_fee[b] as f64 / remaining_weight[b].max(1) as f64;
+            rate_b.total_cmp(&rate_a)
+        });
+
+        let picked = candidates.into_iter().find_map(|txid| {
+            let package: std::collections::HashSet<Txid> = entries[&txid]
+                .ancestors
+                .iter()
+                .filter(|ancestor_txid| !included.contains(*ancestor_txid))
+                .copied()
+                .collect();
+            let package_weight: u64 =
+                package.iter().map(|a| entries[a].weight).sum();
+            (package_weight <= budget_left).then_some((txid, package))
+        });
+        let Some((txid, package)) = picked else { break };
+
+        let mut order = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        topo_order_package(txid, entries, &package, &mut visited, &mut order);
+
+        for package_txid in order {
+            included.insert(package_txid);
+            let entry = &entries[&package_txid];
+            budget_left -= entry.weight;
+            template.fees.insert(package_txid, entry.fee);
+            template.total_fee_sat += entry.fee;
+            template.total_weight += entry.weight;
+            template.txids.push(package_txid);
+            for descendant_txid in &entry.descendants {
+                if !included.contains(descendant_txid) {
+                    *remaining_fee.get_mut(descendant_txid).unwrap() -=
+                        entry.fee;
+                    *remaining_weight.get_mut(descendant_txid).unwrap() -=
+                        entry.weight;
+                }
+            }
+        }
+    }
+
+    template
+}
+
+/// Aggregate stats over the synced mempool, cheap enough to poll as a
+/// health/observability endpoint.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MempoolInfo {
+    /// Number of unconfirmed txs in the mempool.
+    pub tx_count: usize,
+    /// Total virtual size (vbytes) of all mempool txs.
+    pub total_vsize: u64,
+    /// Total weight (weight units) of all mempool txs.
+    pub total_weight: u64,
+    /// Total fees (sats) of all mempool txs.
+    pub total_fee_sat: u64,
+    /// Lowest fee rate (sat/vB) among mempool txs.
+    pub min_fee_rate: Option<f64>,
+    /// Highest fee rate (sat/vB) among mempool txs.
+    pub max_fee_rate: Option<f64>,
+    /// Number of orphan txs currently waiting on a missing parent.
+    pub orphan_count: usize,
+}
+
+/// Running totals backing [`MempoolSync::info`], updated in lockstep with
+/// every `Mempool::insert`/`remove` the sync task performs, instead of
+/// rescanning all txs. The counts and totals are read in O(1); the min/max
+/// fee rates are an O(log n) lookup in `fee_rates`.
+#[derive(Debug, Default)]
+struct MempoolStatsInner {
+    tx_count: usize,
+    total_vsize: u64,
+    total_weight: u64,
+    total_fee_sat: u64,
+    /// Each tracked tx's own vsize, so totals can be unwound on removal.
+    vsize_by_txid: HashMap<Txid, u64>,
+    /// Fee rate (sat/vB), as an IEEE 754 bit pattern (monotonic for the
+    /// non-negative rates we deal with), mapped to the txids currently at
+    /// that exact rate. A `BTreeMap` keeps the min/max an `O(log n)` lookup
+    /// away.
+    fee_rates: std::collections::BTreeMap<u64, LinkedHashSet<Txid>>,
+    /// Each tracked tx's own fee-rate key, so it can be found again on
+    /// removal.
+    fee_rate_by_txid: HashMap<Txid, u64>,
+}
+
+impl MempoolStatsInner {
+    fn record_insert(&mut self, txid: Txid, tx: &Transaction, fee_sat: u64) {
+        if self.vsize_by_txid.contains_key(&txid) {
+            return;
+        }
+        let vsize = tx.vsize() as u64;
+        self.tx_count += 1;
+        self.total_vsize += vsize;
+        self.total_weight += tx.weight().to_wu();
+        self.total_fee_sat += fee_sat;
+        self.vsize_by_txid.insert(txid, vsize);
+        let fee_rate_bits = (fee_sat as f64 / vsize as f64).to_bits();
+        self.fee_rates.entry(fee_rate_bits).or_default().replace(txid);
+        self.fee_rate_by_txid.insert(txid, fee_rate_bits);
+    }
+
+    fn record_remove(&mut self, txid: &Txid, tx: &Transaction, fee_sat: u64) {
+        let Some(vsize) = self.vsize_by_txid.remove(txid) else {
+            return;
+        };
+        self.tx_count -= 1;
+        self.total_vsize -= vsize;
+        self.total_weight -= tx.weight().to_wu();
+        self.total_fee_sat -= fee_sat;
+        if let Some(fee_rate_bits) = self.fee_rate_by_txid.remove(txid) {
+            if let Some(txids) = self.fee_rates.get_mut(&fee_rate_bits) {
+                txids.remove(txid);
+                if txids.is_empty() {
+                    self.fee_rates.remove(&fee_rate_bits);
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self, orphan_count: usize) -> MempoolInfo {
+        MempoolInfo {
+            tx_count: self.tx_count,
+            total_vsize: self.total_vsize,
+            total_weight: self.total_weight,
+            total_fee_sat: self.total_fee_sat,
+            min_fee_rate: self
+                .fee_rates
+                .keys()
+                .next()
+                .copied()
+                .map(f64::from_bits),
+            max_fee_rate: self
+                .fee_rates
+                .keys()
+                .next_back()
+                .copied()
+                .map(f64::from_bits),
+            orphan_count,
+        }
+    }
+}
+
+/// Shared handle to a [`MempoolStatsInner`], so [`MempoolSync::info`] can
+/// read it without the sync task's mutable state.
+#[derive(Debug, Default)]
+struct MempoolStats(Mutex<MempoolStatsInner>);
+
+impl MempoolStats {
+    fn record_insert(&self, txid: Txid, tx: &Transaction, fee_sat: u64) {
+        self.0.lock().unwrap().record_insert(txid, tx, fee_sat);
+    }
+
+    fn record_remove(&self, txid: &Txid, tx: &Transaction, fee_sat: u64) {
+        self.0.lock().unwrap().record_remove(txid, tx, fee_sat);
+    }
+
+    fn snapshot(&self, orphan_count: usize) -> MempoolInfo {
+        self.0.lock().unwrap().snapshot(orphan_count)
+    }
+}
+
 pub struct MempoolSync {
     mempool: Arc<RwLock<Mempool>>,
+    ctx: Arc<SyncContext>,
     task: JoinHandle<()>,
 }
 
@@ -375,10 +1296,12 @@ impl MempoolSync {
         Enforcer: CusfEnforcer + Send + 'static,
     {
         let mempool = Arc::new(RwLock::new(mempool));
+        let ctx = Arc::new(SyncContext::new());
         let task = spawn(
             task(
                 enforcer,
                 mempool.clone(),
+                ctx.clone(),
                 rpc_client.clone(),
                 sequence_stream,
             )
@@ -387,7 +1310,7 @@ impl MempoolSync {
                 tracing::error!("{err:#}");
             }),
         );
-        Self { mempool, task }
+        Self { mempool, ctx, task }
     }
 
     pub async fn with_mempool<F, Output>(&self, f: F) -> Output
@@ -397,10 +1320,411 @@ impl MempoolSync {
         let mempool_read = self.mempool.read().await;
         f(&mempool_read)
     }
+
+    /// Look up why a recently-seen txid was rejected, if it was.
+    pub fn recent_rejects(&self, txid: &Txid) -> Option<RejectReason> {
+        self.ctx.rejects.get(txid)
+    }
+
+    /// Subscribe to tx rejections as they happen. Subscribers that fall too
+    /// far behind receive [`broadcast::error::RecvError::Lagged`] rather
+    /// than stalling the sync task.
+    pub fn subscribe_rejects(&self) -> broadcast::Receiver<TxRejected> {
+        self.ctx.rejects.subscribe()
+    }
+
+    /// Aggregate stats over the synced mempool. Backed by running totals
+    /// maintained on every `Mempool::insert`/`remove`, so this never
+    /// rescans all txs: the counts and totals are `O(1)`, the min/max fee
+    /// rates an `O(log n)` lookup.
+    pub fn info(&self) -> MempoolInfo {
+        self.ctx.stats.snapshot(self.ctx.orphans.len())
+    }
+
+    /// Assemble a candidate block template from the synced mempool, ordered
+    /// by ancestor (CPFP) fee rate and bounded to `max_weight`. Backed by an
+    /// ancestor/descendant index maintained incrementally on every
+    /// `Mempool::insert`/`remove`, so this doesn't need to hold the
+    /// mempool's `RwLock` or rebuild the index from scratch.
+    pub fn block_template(&self, max_weight: u64) -> BlockTemplate {
+        let entries = self.ctx.ancestor_index.snapshot();
+        build_block_template(&entries, max_weight)
+    }
+
+    /// Subscribe to mempool and chain-tip changes as they happen, so that
+    /// multiple enforcer components can react in real time without holding
+    /// the mempool's `RwLock` or busy-polling [`Self::with_mempool`].
+    /// Subscribers that fall too far behind receive
+    /// [`broadcast::error::RecvError::Lagged`] rather than stalling the sync
+    /// task.
+    pub fn subscribe(&self) -> broadcast::Receiver<MempoolEvent> {
+        self.ctx.events.subscribe()
+    }
 }
 
 impl Drop for MempoolSync {
     fn drop(&mut self) {
         self.task.abort()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, transaction::Version, ScriptBuf, Sequence, TxIn,
+        TxOut, Witness,
+    };
+
+    use super::*;
+
+    /// A minimal tx with the given inputs and a single output of
+    /// `output_value_sat`, for ancestor-index tests that only care about the
+    /// package graph, not script validity.
+    fn test_tx(inputs: Vec<OutPoint>, output_value_sat: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(output_value_sat),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    /// A low-fee parent should only be selected because its high-fee child
+    /// pulls it in via the package's combined ancestor fee rate, not on its
+    /// own fee rate.
+    #[test]
+    fn cpfp_child_pulls_in_low_fee_parent() {
+        let parent = test_tx(vec![], 50_000);
+        let parent_txid = parent.compute_txid();
+        let parent_fee = 1;
+
+        let child = test_tx(
+            vec![OutPoint {
+                txid: parent_txid,
+                vout: 0,
+            }],
+            1_000,
+        );
+        let child_txid = child.compute_txid();
+        let child_fee = 100_000;
+
+        let mut index = AncestorIndexInner::default();
+        index.record_insert(parent_txid, &parent, parent_fee);
+        index.record_insert(child_txid, &child, child_fee);
+
+        // On its own fee rate, the parent would never be picked: its fee
+        // rate is far below the child's. It's only included because it's an
+        // unincluded ancestor of the package the child pulls in.
+        assert!(
+            index.entries[&parent_txid].fee as f64
+                / index.entries[&parent_txid].weight as f64
+                < index.entries[&child_txid].fee as f64
+                    / index.entries[&child_txid].weight as f64
+        );
+
+        let template = build_block_template(&index.entries, u64::MAX);
+
+        assert_eq!(template.txids, vec![parent_txid, child_txid]);
+        assert_eq!(template.total_fee_sat, parent_fee + child_fee);
+    }
+
+    /// A package that doesn't fit the weight budget is skipped, rather than
+    /// pulling in an ancestor it can't afford.
+    #[test]
+    fn cpfp_package_over_budget_is_skipped() {
+        let parent = test_tx(vec![], 50_000);
+        let parent_txid = parent.compute_txid();
+        let parent_fee = 1;
+
+        let child = test_tx(
+            vec![OutPoint {
+                txid: parent_txid,
+                vout: 0,
+            }],
+            1_000,
+        );
+        let child_txid = child.compute_txid();
+        let child_fee = 100_000;
+
+        let mut index = AncestorIndexInner::default();
+        index.record_insert(parent_txid, &parent, parent_fee);
+        index.record_insert(child_txid, &child, child_fee);
+
+        let template = build_block_template(&index.entries, 1);
+
+        assert!(template.txids.is_empty());
+    }
+
+    /// A [`CusfEnforcer`] that accepts every tx, for sync-task tests that
+    /// don't care about enforcement decisions.
+    struct AcceptAllEnforcer;
+
+    impl CusfEnforcer for AcceptAllEnforcer {
+        type AcceptTxError = std::convert::Infallible;
+
+        fn accept_tx(
+            &mut self,
+            _tx: &Transaction,
+        ) -> Result<bool, Self::AcceptTxError> {
+            Ok(true)
+        }
+    }
+
+    /// A disconnected-block tx not yet in `tx_cache` must still make it back
+    /// into the mempool once its body is fetched, rather than being
+    /// stranded in `tx_cache` forever. Regression test for a bug where
+    /// `reinsert_disconnected_block_txs` requested the body but nothing
+    /// re-drove reinsertion once it arrived.
+    #[test]
+    fn reorg_reinserts_tx_fetched_after_being_requested() {
+        let mut mempool = Mempool::default();
+        let mut sync_state = SyncState::default();
+        let ctx = SyncContext::new();
+        let mut enforcer = AcceptAllEnforcer;
+
+        let tx = test_tx(vec![], 0);
+        let txid = tx.compute_txid();
+
+        // The tx isn't in `tx_cache` yet, so reinsertion only requests it.
+        reinsert_disconnected_block_txs(
+            &mut enforcer,
+            &mut mempool,
+            &mut sync_state,
+            &ctx,
+            &[txid],
+        )
+        .unwrap();
+        assert!(sync_state.pending_reinsert.contains(&txid));
+        assert!(!mempool.txs.0.contains_key(&txid));
+
+        // The body arrives...
+        let fetched_txid = handle_resp_tx(&mut sync_state, tx);
+        assert_eq!(fetched_txid, txid);
+
+        // ...which should re-drive the pending reinsertion rather than
+        // leaving the tx stranded in `tx_cache`.
+        try_resolve_pending_reinsert(
+            &mut enforcer,
+            &mut mempool,
+            &mut sync_state,
+            &ctx,
+            &fetched_txid,
+        )
+        .unwrap();
+
+        assert!(!sync_state.pending_reinsert.contains(&txid));
+        assert!(mempool.txs.0.contains_key(&txid));
+    }
+
+    /// A mempool tx that double-spends an input consumed by a
+    /// disconnected-block tx must be dropped even when that tx's body only
+    /// arrives *after* disconnection (the deferred/`pending_reinsert`
+    /// path), not just when it's already cached at disconnect time.
+    /// Regression test for a bug where `drop_conflicting_txs` only ran
+    /// inside the synchronous sweep in `reinsert_disconnected_block_txs`,
+    /// never re-running once a deferred reinsert resolved.
+    #[test]
+    fn deferred_reinsert_drops_conflicting_mempool_tx() {
+        let mut mempool = Mempool::default();
+        let mut sync_state = SyncState::default();
+        let ctx = SyncContext::new();
+        let mut enforcer = AcceptAllEnforcer;
+
+        // A parent tx whose single output both the conflicting and
+        // reinserted txs below spend. Cached so their fees can be computed.
+        let parent_tx = test_tx(vec![], 10_000);
+        let parent_txid = parent_tx.compute_txid();
+        sync_state.tx_cache.insert(parent_txid, parent_tx);
+        let shared_outpoint = OutPoint {
+            txid: parent_txid,
+            vout: 0,
+        };
+
+        // A conflicting tx, already in the mempool, spending the same
+        // outpoint the reinserted tx's only input spends.
+        // Its output value differs from the reinserted tx's below, so the two
+        // have distinct txids.
+        let conflicting_tx = test_tx(vec![shared_outpoint], 2);
+        let conflicting_txid = conflicting_tx.compute_txid();
+        mempool.insert(conflicting_tx, 1_000).unwrap();
+        assert!(mempool.txs.0.contains_key(&conflicting_txid));
+
+        let reinserted_tx = test_tx(vec![shared_outpoint], 1);
+        let reinserted_txid = reinserted_tx.compute_txid();
+
+        // Not yet in `tx_cache`, so reinsertion only requests it.
+        reinsert_disconnected_block_txs(
+            &mut enforcer,
+            &mut mempool,
+            &mut sync_state,
+            &ctx,
+            &[reinserted_txid],
+        )
+        .unwrap();
+        assert!(sync_state.pending_reinsert.contains(&reinserted_txid));
+        // The conflicting tx hasn't been touched yet: the synchronous sweep
+        // never saw `reinserted_txid` in the mempool or cache.
+        assert!(mempool.txs.0.contains_key(&conflicting_txid));
+
+        // The body arrives, re-driving the deferred reinsert...
+        let fetched_txid = handle_resp_tx(&mut sync_state, reinserted_tx);
+        assert_eq!(fetched_txid, reinserted_txid);
+        try_resolve_pending_reinsert(
+            &mut enforcer,
+            &mut mempool,
+            &mut sync_state,
+            &ctx,
+            &fetched_txid,
+        )
+        .unwrap();
+
+        // ...which must now also drop the conflicting mempool tx.
+        assert!(mempool.txs.0.contains_key(&reinserted_txid));
+        assert!(!mempool.txs.0.contains_key(&conflicting_txid));
+    }
+
+    /// An orphan evicted because its missing parent is provably
+    /// unresolvable (e.g. the parent confirmed or was conflicted out) must
+    /// be recorded as a rejection, not silently dropped -- otherwise it
+    /// vanishes from `recent_rejects`/`subscribe_rejects` with no trace, even
+    /// though [`RejectReason::MissingInputs`] exists for exactly this case.
+    #[test]
+    fn evicted_orphan_is_recorded_as_rejected() {
+        let ctx = SyncContext::new();
+        let mut reject_events = ctx.events.subscribe();
+
+        let parent_txid = test_tx(vec![], 0).compute_txid();
+        let orphan = test_tx(vec![OutPoint { txid: parent_txid, vout: 0 }], 1);
+        let orphan_txid = orphan.compute_txid();
+
+        let evicted = ctx.orphans.insert(orphan_txid, orphan, [parent_txid]);
+        assert!(evicted.is_empty());
+        assert_eq!(ctx.orphans.len(), 1);
+
+        // The parent is now provably unresolvable (e.g. confirmed or
+        // conflicted out), so the orphan can never be completed.
+        let evicted = ctx.orphans.evict_parent(&parent_txid);
+        ctx.reject_evicted_orphans(evicted);
+
+        assert_eq!(ctx.orphans.len(), 0);
+        assert!(matches!(
+            ctx.rejects.get(&orphan_txid),
+            Some(RejectReason::MissingInputs)
+        ));
+        assert!(matches!(
+            reject_events.try_recv(),
+            Ok(MempoolEvent::TxRejected {
+                txid,
+                reason: RejectReason::MissingInputs,
+            }) if txid == orphan_txid
+        ));
+    }
+
+    /// After a 2-block reorg -- disconnecting the old tip, then connecting
+    /// two new blocks -- driven entirely over the ZMQ sequence stream, the
+    /// mempool must end up in the same observable state (tip + tx set) as
+    /// syncing the new chain from scratch would produce: the old block's tx
+    /// survives as an unconfirmed mempool tx (it wasn't double-spent by the
+    /// new chain), and the tip matches the new chain's head.
+    #[test]
+    fn reorg_two_blocks_matches_fresh_sync() {
+        let genesis_hash = BlockHash::all_zeros();
+        let old_hash = BlockHash::from_byte_array([1; 32]);
+        let new_hash_1 = BlockHash::from_byte_array([2; 32]);
+        let new_hash_2 = BlockHash::from_byte_array([3; 32]);
+
+        let old_tx = test_tx(vec![], 1);
+        let old_txid = old_tx.compute_txid();
+        let new_tx_1 = test_tx(vec![], 2);
+        let new_tx_2 = test_tx(vec![], 3);
+
+        let old_block = bip300301::client::Block {
+            hash: old_hash,
+            tx: vec![old_txid],
+            previousblockhash: Some(genesis_hash),
+            ..Default::default()
+        };
+        let new_block_1 = bip300301::client::Block {
+            hash: new_hash_1,
+            tx: vec![new_tx_1.compute_txid()],
+            previousblockhash: Some(genesis_hash),
+            ..Default::default()
+        };
+        let new_block_2 = bip300301::client::Block {
+            hash: new_hash_2,
+            tx: vec![new_tx_2.compute_txid()],
+            previousblockhash: Some(new_hash_1),
+            ..Default::default()
+        };
+
+        let mut mempool = Mempool::default();
+        mempool.chain.tip = old_hash;
+        mempool.chain.blocks.insert(old_hash, old_block.clone());
+        let mut sync_state = SyncState::default();
+        // The old block's tx body is already known (e.g. it was in the
+        // mempool before confirming), so disconnection can reinsert it
+        // synchronously rather than deferring via `pending_reinsert`.
+        sync_state.tx_cache.insert(old_txid, old_tx.clone());
+        let ctx = SyncContext::new();
+        let mut enforcer = AcceptAllEnforcer;
+
+        // 1. The old tip is disconnected, over the sequence stream.
+        sync_state
+            .seq_message_queue
+            .push_back(SequenceMessage::BlockHashDisconnected(old_hash, 0));
+        assert!(try_apply_next_seq_message(
+            &mut enforcer,
+            &mut mempool,
+            &mut sync_state,
+            &ctx,
+        )
+        .unwrap());
+        assert_eq!(mempool.chain.tip, genesis_hash);
+        assert!(mempool.txs.0.contains_key(&old_txid));
+
+        // 2. Two new blocks connect in turn, each fetched and handled as a
+        // response to the sequence stream's connect notification.
+        for block in [new_block_1.clone(), new_block_2.clone()] {
+            sync_state.seq_message_queue.push_back(
+                SequenceMessage::BlockHashConnected(block.hash, 0),
+            );
+            handle_resp_block(
+                &mut enforcer,
+                &mut mempool,
+                &mut sync_state,
+                &ctx,
+                block,
+            )
+            .unwrap();
+        }
+        assert_eq!(mempool.chain.tip, new_hash_2);
+
+        // The old block's tx is still an unconfirmed mempool tx: it was
+        // never double-spent by the new chain, so a from-scratch sync
+        // (which would just see it via a fresh `getrawmempool`) would also
+        // end up with it present.
+        let mut from_scratch = Mempool::default();
+        from_scratch.chain.tip = new_hash_2;
+        from_scratch.insert(old_tx, 0).unwrap();
+        assert_eq!(
+            mempool.txs.0.keys().collect::<std::collections::HashSet<_>>(),
+            from_scratch
+                .txs
+                .0
+                .keys()
+                .collect::<std::collections::HashSet<_>>(),
+        );
+        assert_eq!(mempool.chain.tip, from_scratch.chain.tip);
+    }
+}